@@ -1,39 +1,323 @@
 use bevy::{
+    app::stage,
+    diagnostic::{Diagnostics, FrameTimeDiagnosticsPlugin},
     prelude::*,
     render::{
+        draw::Draw,
         mesh::shape,
     },
 };
-static mut FRAMES:i32 = 0;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use std::str::FromStr;
+use structopt::StructOpt;
+
+/// How the cube workload is laid out in the scene.
+#[derive(Debug, Clone, Copy)]
+enum Layout {
+    /// Single row along X, the original degenerate layout.
+    Row,
+    /// Even cubic grid filling the bounded volume.
+    Grid,
+    /// Seeded random scatter within the bounded volume.
+    Scatter,
+}
+
+impl FromStr for Layout {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "row" => Ok(Layout::Row),
+            "grid" => Ok(Layout::Grid),
+            "scatter" => Ok(Layout::Scatter),
+            other => Err(format!("unknown layout '{}', expected row|grid|scatter", other)),
+        }
+    }
+}
+
+/// Compute the `cube_count` cube translations for the requested layout. The
+/// scatter layout draws from a seeded RNG so placement is reproducible per
+/// seed but varied across seeds.
+fn cube_positions(config: &BenchConfig) -> Vec<Vec3> {
+    let count = config.cube_count as usize;
+    let extent = config.volume_extent;
+    match config.layout {
+        Layout::Row => {
+            let half = config.cube_count as i32 / 2;
+            (-half..(config.cube_count as i32 - half))
+                .map(|x| Vec3::new(x as f32, 1.0, 0.0))
+                .collect()
+        }
+        Layout::Grid => {
+            let per_axis = (count as f32).cbrt().ceil() as usize;
+            let per_axis = per_axis.max(1);
+            let step = if per_axis > 1 {
+                (2.0 * extent) / (per_axis - 1) as f32
+            } else {
+                0.0
+            };
+            let mut positions = Vec::with_capacity(count);
+            for i in 0..count {
+                let x = i % per_axis;
+                let y = (i / per_axis) % per_axis;
+                let z = i / (per_axis * per_axis);
+                positions.push(Vec3::new(
+                    -extent + x as f32 * step,
+                    1.0 + y as f32 * step,
+                    -extent + z as f32 * step,
+                ));
+            }
+            positions
+        }
+        Layout::Scatter => {
+            let mut rng = StdRng::seed_from_u64(config.seed);
+            (0..count)
+                .map(|_| {
+                    Vec3::new(
+                        rng.gen_range(-extent..extent),
+                        1.0 + rng.gen_range(0.0..extent),
+                        rng.gen_range(-extent..extent),
+                    )
+                })
+                .collect()
+        }
+    }
+}
+
+/// Deterministic frame budget for the benchmark run.
+///
+/// A single late-stage system owns `current`, so the rotation systems stay pure
+/// and frame counting no longer races across the parallel scheduler.
+struct BenchmarkClock {
+    warmup: u32,
+    measure: u32,
+    current: u32,
+}
+
+/// Render tuning knobs. bevy 0.1's PBR pipeline has no culling setting to wire
+/// through, so this is a hand-rolled visibility toggle rather than the engine's
+/// own path.
+struct RenderSettings {
+    facing_cull_enabled: bool,
+}
+
+/// Cheap half-space (hemisphere) cull: toggles each cube's `Draw.is_visible`
+/// using the sign of `(translation - camera) . forward`, so cubes behind the
+/// orbiting camera are skipped when the toggle is on. This is not FOV-aware
+/// frustum culling; with the toggle off every cube stays visible, giving MLOS a
+/// discrete axis with a measurable effect on frame time.
+fn facing_cull_system(
+    time: Res<Time>,
+    config: Res<BenchConfig>,
+    settings: Res<RenderSettings>,
+    mut query: Query<(&Rotator, &Translation, &mut Draw)>,
+) {
+    let t = time.seconds_since_startup as f32;
+    let radius = config.camera_orbit_radius;
+    let camera = Vec3::new(t.cos() * radius, 4.0, t.sin() * radius);
+    let forward = Vec3::new(0.0, 0.0, 0.0) - camera;
+    for (_rotator, translation, mut draw) in &mut query.iter() {
+        draw.is_visible = if settings.facing_cull_enabled {
+            (translation.0 - camera).dot(forward) > 0.0
+        } else {
+            true
+        };
+    }
+}
+
+/// Captured per-frame delta-times (seconds) for the measurement window.
+#[derive(Default)]
+struct FrameStats {
+    samples: Vec<f32>,
+}
+
+/// Percentile of an already-sorted sample slice, indexing at `ceil(q * (n-1))`.
+fn percentile(sorted: &[f32], q: f32) -> f32 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+    let idx = (q * (sorted.len() - 1) as f32).ceil() as usize;
+    sorted[idx]
+}
+
+/// Print mean, min/max and p50/p90/p99 of the captured frame times as a single
+/// JSON line so MLOS can parse an objective (e.g. p99) out of stdout.
+fn report_stats(stats: &FrameStats) {
+    let mut sorted = stats.samples.clone();
+    sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let n = sorted.len();
+    let mean = if n == 0 { 0.0 } else { sorted.iter().sum::<f32>() / n as f32 };
+    let min = sorted.first().cloned().unwrap_or(0.0);
+    let max = sorted.last().cloned().unwrap_or(0.0);
+    println!(
+        "{{\"frames\":{},\"mean\":{},\"min\":{},\"max\":{},\"p50\":{},\"p90\":{},\"p99\":{}}}",
+        n,
+        mean,
+        min,
+        max,
+        percentile(&sorted, 0.50),
+        percentile(&sorted, 0.90),
+        percentile(&sorted, 0.99),
+    );
+}
+
+/// Tunable knobs for the cube-storm benchmark.
+///
+/// Parsed from CLI args and falling back to environment variables so MLOS can
+/// sweep the scene across runs without recompiling, then inserted as a Bevy
+/// `Resource` that `setup`, `rotatel`, `facing_cull_system` and `physics_system`
+/// read from.
+#[derive(StructOpt, Debug, Clone)]
+#[structopt(name = "bevybench")]
+struct BenchConfig {
+    /// Number of cubes to spawn in the scene.
+    #[structopt(long, env = "BEVYBENCH_CUBE_COUNT", default_value = "1000")]
+    cube_count: u32,
+
+    /// MSAA sample count for the render pass.
+    #[structopt(long, env = "BEVYBENCH_MSAA_SAMPLES", default_value = "8")]
+    msaa_samples: u32,
+
+    /// Number of warm-up frames to discard before measuring.
+    #[structopt(long, env = "BEVYBENCH_WARMUP_FRAMES", default_value = "60")]
+    warmup_frames: u32,
+
+    /// Number of frames to run before exiting.
+    #[structopt(long, env = "BEVYBENCH_MEASURE_FRAMES", default_value = "240")]
+    measure_frames: u32,
+
+    /// Radius of the orbiting camera path.
+    #[structopt(long, env = "BEVYBENCH_CAMERA_ORBIT_RADIUS", default_value = "20.0")]
+    camera_orbit_radius: f32,
+
+    /// Cube layout mode: row, grid, or scatter.
+    #[structopt(long, env = "BEVYBENCH_LAYOUT", default_value = "row")]
+    layout: Layout,
+
+    /// RNG seed for the scatter layout (fixed control for reproducibility).
+    #[structopt(long, env = "BEVYBENCH_SEED", default_value = "0")]
+    seed: u64,
+
+    /// Half-extent of the bounded volume used by the grid/scatter layouts.
+    #[structopt(long, env = "BEVYBENCH_VOLUME_EXTENT", default_value = "500.0")]
+    volume_extent: f32,
+
+    /// Whether the camera-facing (hemisphere) cull is enabled for the scene.
+    #[structopt(
+        long = "facing-cull",
+        env = "BEVYBENCH_FACING_CULL",
+        default_value = "true",
+        parse(try_from_str)
+    )]
+    facing_cull: bool,
+
+    /// Drop the cubes under a hand-rolled gravity integrator (CPU-bound mode).
+    #[structopt(
+        long = "physics",
+        env = "BEVYBENCH_PHYSICS",
+        default_value = "false",
+        parse(try_from_str)
+    )]
+    physics: bool,
+
+    /// Gravity magnitude (m/s^2) applied along -Y in the physics mode.
+    #[structopt(long, env = "BEVYBENCH_GRAVITY", default_value = "9.81")]
+    gravity: f32,
+
+    /// Integration substeps per frame in the physics mode.
+    #[structopt(long, env = "BEVYBENCH_SUBSTEPS", default_value = "6")]
+    substeps: u32,
+}
+
 fn main() {
+    let config = BenchConfig::from_args();
+    let clock = BenchmarkClock {
+        warmup: config.warmup_frames,
+        measure: config.measure_frames,
+        current: 0,
+    };
     App::build()
-        .add_resource(Msaa { samples: 8 })
+        .add_resource(Msaa { samples: config.msaa_samples })
+        .add_resource(RenderSettings { facing_cull_enabled: config.facing_cull })
+        .add_resource(config)
+        .add_resource(clock)
+        .init_resource::<FrameStats>()
         .add_default_plugins()
+        .add_plugin(FrameTimeDiagnosticsPlugin)
         .add_startup_system(setup.system())
         .add_system(rotator_system.system())
         .add_system(rotatel.system())
+        .add_system(facing_cull_system.system())
+        .add_system(physics_system.system())
+        .add_system_to_stage(stage::LAST, benchmark_clock_system.system())
         .run();
 }
 
+/// Advances the frame budget once per frame in a late stage: records frame
+/// times after warm-up and reports the collected statistics on the final frame.
+fn benchmark_clock_system(
+    diagnostics: Res<Diagnostics>,
+    mut clock: ResMut<BenchmarkClock>,
+    mut stats: ResMut<FrameStats>,
+) {
+    clock.current += 1;
+    if clock.current > clock.warmup {
+        if let Some(frame_time) = diagnostics
+            .get(FrameTimeDiagnosticsPlugin::FRAME_TIME)
+            .and_then(|diagnostic| diagnostic.value())
+        {
+            stats.samples.push(frame_time as f32);
+        }
+    }
+    if clock.current >= clock.measure {
+        report_stats(&stats);
+        std::process::exit(0);
+    }
+}
+
 struct CameraOperator;
 struct Rotator;
+struct Velocity(Vec3);
+
+/// Hand-rolled semi-implicit Euler integrator. Each cube carrying a `Velocity`
+/// is accelerated by gravity and advanced over `substeps` sub-iterations per
+/// frame, coming to rest on the ground plane. This is a CPU-bound workload
+/// distinct from the pure-render path; `gravity`/`substeps` are the axes MLOS
+/// tunes against the same frame-time objective. Cubes only gain a `Velocity`
+/// when physics is enabled, so this is a no-op in the default render mode.
+fn physics_system(
+    time: Res<Time>,
+    config: Res<BenchConfig>,
+    mut query: Query<(&mut Velocity, &mut Translation)>,
+) {
+    let substeps = config.substeps.max(1);
+    let dt = time.delta_seconds / substeps as f32;
+    for (mut velocity, mut translation) in &mut query.iter() {
+        for _ in 0..substeps {
+            let vy = velocity.0.y() - config.gravity * dt;
+            velocity.0.set_y(vy);
+            let mut pos = translation.0 + velocity.0 * dt;
+            if pos.y() < 0.5 {
+                pos.set_y(0.5);
+                velocity.0.set_y(0.0);
+            }
+            translation.0 = pos;
+        }
+    }
+}
 
 /// rotates the parent, which will result in the child also rotating
 fn rotator_system(time: Res<Time>, mut query: Query<(&Rotator, &mut Rotation)>) {
     for (_rotator, mut rotation) in &mut query.iter() {
-        rotation.0 = rotation.0 * Quat::from_rotation_x(3.0 * time.delta_seconds);
-    }
-    unsafe{
-        FRAMES = FRAMES + 1;
-        if FRAMES >= 240 {
-            std::process::exit(0);
-        }
+        rotation.0 *= Quat::from_rotation_x(3.0 * time.delta_seconds);
     }
 }
 
 
 fn setup(
     mut commands: Commands,
+    config: Res<BenchConfig>,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
 ) {
@@ -57,29 +341,31 @@ fn setup(
         })
         .with(CameraOperator);
 
-    for x in -500..500 {
+    for pos in cube_positions(&config) {
         commands.spawn(PbrComponents {
             mesh: meshes.add(Mesh::from(shape::Cube { size: 1.0 })),
             material: materials.add(Color::rgb(0.5, 0.4, 0.3).into()),
-            translation: Translation::new(x as f32, 1.0, 0.0),
+            translation: Translation::new(pos.x(), pos.y(), pos.z()),
             ..Default::default()
         }).with(Rotator);
+        if config.physics {
+            commands.with(Velocity(Vec3::new(0.0, 0.0, 0.0)));
+        }
     }
 
 }
 
-fn rotatel(time: Res<Time>, mut query: Query<(&CameraOperator, &mut Transform)>) {
-    
+fn rotatel(time: Res<Time>, config: Res<BenchConfig>, mut query: Query<(&CameraOperator, &mut Transform)>) {
+
     for (_camera_operator, mut transform) in &mut query.iter() {
         //println!("trans {},{}", time.delta_seconds, transform.value);
+        let radius = config.camera_orbit_radius;
         transform.value =  Mat4::face_toward(
-            Vec3::new((time.seconds_since_startup as f32).cos()*20.0,4.0,(time.seconds_since_startup as f32).sin()*20.0),
+            Vec3::new((time.seconds_since_startup as f32).cos()*radius,4.0,(time.seconds_since_startup as f32).sin()*radius),
             Vec3::new(0.0, 0.0, 0.0),
             Vec3::new(0.0, 1.0, 0.0),
         );// .mul_vec4(Vec4::zero());
      //   rotation.0 = rotation.0 * Quat::from_rotation_x(3.0 * time.delta_seconds);
     }
-    
-}
-
 
+}